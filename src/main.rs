@@ -1,11 +1,23 @@
 #![forbid(unsafe_code)]
 use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::io;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::task::{Context, Poll};
 use std::time::SystemTime;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, copy_bidirectional},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf, copy_bidirectional},
     net::{TcpListener, TcpStream},
     time::timeout,
 };
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme};
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::CertifiedKey;
 use chrono::{SecondsFormat, Utc};
 use serde::Deserialize;
 use uuid::Uuid;
@@ -18,6 +30,7 @@ const HTTP_HEADER_READ_TIMEOUT: Duration = Duration::from_secs(5);
 const TLS_CLIENTHELLO_READ_TIMEOUT: Duration = Duration::from_secs(5);
 const BACKEND_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
 const PROXY_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 5);
+const HEALTH_PROBE_MAX_BYTES: usize = 4 * 1024;
 
 #[derive(Clone, Copy)]
 struct Cur<'a> {
@@ -56,18 +69,284 @@ impl<'a> Cur<'a> {
     }
 }
 
+/// A stream that replays an already-read byte prefix before delegating to the
+/// underlying socket. We peek the ClientHello off the wire to read SNI, so when
+/// we later want to terminate TLS those bytes have to be handed to the acceptor
+/// intact. `TcpStream` is `Unpin`, so this needs no pin-projection (and keeps us
+/// inside `#![forbid(unsafe_code)]`).
+struct PrefixStream {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: TcpStream,
+}
+
+impl PrefixStream {
+    fn new(prefix: Vec<u8>, inner: TcpStream) -> Self {
+        Self { prefix, pos: 0, inner }
+    }
+}
+
+impl AsyncRead for PrefixStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let me = self.get_mut();
+        if me.pos < me.prefix.len() {
+            let remaining = &me.prefix[me.pos..];
+            let n = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..n]);
+            me.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut me.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for PrefixStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// SNI-aware certificate resolver keyed on the hostnames that are configured for
+/// TLS termination. Passthrough hosts never reach here, so a miss simply aborts
+/// the handshake.
+#[derive(Debug)]
+struct CertResolver {
+    keys: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let name = client_hello.server_name()?;
+        self.keys.get(name).cloned()
+    }
+}
+
+/// A parsed backend entry. Bare `host:port` stays plaintext; `http://` and
+/// `https://` select the scheme explicitly. `path_prefix` is captured for
+/// future path-aware routing but not yet consulted by the splice proxy.
+#[derive(Debug, Clone)]
+struct BackendTarget {
+    is_tls: bool,
+    host: String,
+    port: u16,
+    #[allow(dead_code)]
+    path_prefix: String,
+}
+
+impl BackendTarget {
+    fn authority(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+fn split_host_port(authority: &str, default_port: u16) -> (String, u16) {
+    if let Some(rest) = authority.strip_prefix('[') {
+        if let Some(end) = rest.find(']') {
+            let host = format!("[{}]", &rest[..end]);
+            let port = rest[end + 1..]
+                .strip_prefix(':')
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(default_port);
+            return (host, port);
+        }
+    }
+    match authority.rsplit_once(':') {
+        Some((h, p)) if !h.contains(':') => match p.parse::<u16>() {
+            Ok(port) => (h.to_string(), port),
+            Err(_) => (authority.to_string(), default_port),
+        },
+        _ => (authority.to_string(), default_port),
+    }
+}
+
+/// Split a backend entry into `(is_tls, host, port, path_prefix)`. Accepts bare
+/// `host:port`, `http://host[:port]` and `https://host[:port]`, defaulting the
+/// port from the scheme (80/443) and the path prefix to `/`.
+fn parse_backend_uri(entry: &str) -> BackendTarget {
+    let (scheme, rest) = match entry.split_once("://") {
+        Some((s, r)) => (Some(s.to_ascii_lowercase()), r),
+        None => (None, entry),
+    };
+    let is_tls = matches!(scheme.as_deref(), Some("https"));
+    let default_port = if is_tls { 443 } else { 80 };
+
+    let (authority, path_prefix) = match rest.find('/') {
+        Some(i) => (&rest[..i], rest[i..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+
+    let (host, port) = split_host_port(authority, default_port);
+    BackendTarget { is_tls, host, port, path_prefix }
+}
+
+/// Unified backend connection: either a raw socket or a TLS session to the
+/// backend, so the splice proxy can treat both the same.
+enum BackendStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for BackendStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            BackendStream::Tls(s) => Pin::new(s.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for BackendStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            BackendStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            BackendStream::Tls(s) => Pin::new(s.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Plain(s) => Pin::new(s).poll_flush(cx),
+            BackendStream::Tls(s) => Pin::new(s.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            BackendStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            BackendStream::Tls(s) => Pin::new(s.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Connect to a backend entry, re-encrypting with TLS when the entry uses the
+/// `https://` scheme. Honours `BACKEND_CONNECT_TIMEOUT` for both the TCP connect
+/// and the TLS handshake so a stalled backend can never hang the caller.
+async fn connect_backend(shared: &Shared, entry: &str) -> io::Result<BackendStream> {
+    let target = parse_backend_uri(entry);
+    let tcp = match timeout(BACKEND_CONNECT_TIMEOUT, TcpStream::connect(target.authority())).await {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => return Err(e),
+        Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "backend connect timeout")),
+    };
+
+    if !target.is_tls {
+        return Ok(BackendStream::Plain(tcp));
+    }
+
+    let server_name = ServerName::try_from(target.host.clone())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid backend server name"))?;
+    let tls = match timeout(BACKEND_CONNECT_TIMEOUT, shared.backend_connector.connect(server_name, tcp)).await {
+        Ok(Ok(s)) => s,
+        Ok(Err(e)) => return Err(e),
+        Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "backend tls handshake timeout")),
+    };
+    Ok(BackendStream::Tls(Box::new(tls)))
+}
+
+/// Certificate verifier that accepts any backend certificate. Only used when
+/// `backend_tls_insecure` is set, for internal meshes where the backend chain
+/// is not externally rooted.
+#[derive(Debug)]
+struct InsecureServerVerifier;
+
+impl ServerCertVerifier for InsecureServerVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Build the client-side connector used for `https://` backends. Uses the
+/// webpki root set by default, or a no-op verifier when `backend_tls_insecure`
+/// is enabled.
+fn build_backend_connector(config: &Config) -> TlsConnector {
+    let client_config = if config.backend_tls_insecure {
+        ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(InsecureServerVerifier))
+            .with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth()
+    };
+    TlsConnector::from(Arc::new(client_config))
+}
+
 fn find_http_header_end(buf: &[u8]) -> Option<usize> {
     buf.windows(4).position(|w| w == b"\r\n\r\n")
 }
 
-async fn write_http_error(mut client: TcpStream, resp: &[u8]) -> std::io::Result<()> {
+async fn write_http_error<S>(client: &mut S, resp: &[u8]) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
     client.write_all(resp).await
 }
 
-async fn handle_http(
-    mut client: TcpStream,
-    config: Arc<Config>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// Drive the HTTP-layer proxy over any byte stream: accumulate headers, inspect
+/// the `Host:` header, connect the configured backend and splice the two halves
+/// together. The plaintext listener hands this a raw `TcpStream`; the TLS
+/// termination path hands it the decrypted `TlsStream`.
+async fn proxy_http<S>(
+    mut client: S,
+    shared: Arc<Shared>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     let mut buf: Vec<u8> = Vec::with_capacity(2048);
     let mut tmp = [0u8; READ_CHUNK];
 
@@ -92,12 +371,12 @@ async fn handle_http(
     .unwrap_or(None);
 
     let Some(end_pos) = header_end else {
-        let _ = write_http_error(client, b"HTTP/1.1 408 Request Timeout\r\nConnection: close\r\n\r\n").await;
+        let _ = write_http_error(&mut client, b"HTTP/1.1 408 Request Timeout\r\nConnection: close\r\n\r\n").await;
         return Ok(());
     };
 
     if end_pos == usize::MAX {
-        let _ = write_http_error(client, b"HTTP/1.1 431 Request Header Fields Too Large\r\nConnection: close\r\n\r\n").await;
+        let _ = write_http_error(&mut client, b"HTTP/1.1 431 Request Header Fields Too Large\r\nConnection: close\r\n\r\n").await;
         return Ok(());
     }
 
@@ -107,23 +386,23 @@ async fn handle_http(
     let host = match extract_host(header_part) {
         Some(h) => h,
         None => {
-            let _ = write_http_error(client, b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n").await;
+            let _ = write_http_error(&mut client, b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n").await;
             return Ok(());
         }
     };
 
-    let backend_addr = match config.backends.get(&host) {
-        Some(addr) => addr.clone(),
+    let backend_addr = match shared.choose_backend(&host, &[]) {
+        Some(addr) => addr,
         None => {
-            let _ = write_http_error(client, b"HTTP/1.1 502 Bad Gateway\r\nConnection: close\r\n\r\n").await;
+            let _ = write_http_error(&mut client, b"HTTP/1.1 502 Bad Gateway\r\nConnection: close\r\n\r\n").await;
             return Ok(());
         }
     };
 
-    let mut backend = match timeout(BACKEND_CONNECT_TIMEOUT, TcpStream::connect(&backend_addr)).await {
-        Ok(Ok(s)) => s,
-        _ => {
-            let _ = write_http_error(client, b"HTTP/1.1 502 Bad Gateway\r\nConnection: close\r\n\r\n").await;
+    let mut backend = match connect_backend(&shared, &backend_addr).await {
+        Ok(s) => s,
+        Err(_) => {
+            let _ = write_http_error(&mut client, b"HTTP/1.1 502 Bad Gateway\r\nConnection: close\r\n\r\n").await;
             return Ok(());
         }
     };
@@ -142,14 +421,50 @@ async fn handle_http(
     Ok(())
 }
 
+async fn handle_http(
+    client: TcpStream,
+    shared: Arc<Shared>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    proxy_http(client, shared).await
+}
+
+/// Peek the first bytes of a freshly accepted connection and dispatch to the
+/// TLS or plaintext handler. A leading `0x16 0x03` is a TLS handshake record
+/// (content type 22, major version 3); anything else is treated as HTTP. The
+/// bytes we read to decide are handed on to the chosen handler, never dropped.
+async fn handle_detect(
+    mut client: TcpStream,
+    shared: Arc<Shared>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut tmp = [0u8; READ_CHUNK];
+    let n = match timeout(TLS_CLIENTHELLO_READ_TIMEOUT, client.read(&mut tmp)).await {
+        Ok(Ok(0)) | Err(_) => return Ok(()),
+        Ok(Ok(n)) => n,
+        Ok(Err(_)) => return Ok(()),
+    };
+    let prefix = tmp[..n].to_vec();
+
+    if prefix.len() >= 2 && prefix[0] == 0x16 && prefix[1] == 0x03 {
+        handle_https(client, shared, prefix).await
+    } else {
+        proxy_http(PrefixStream::new(prefix, client), shared).await
+    }
+}
+
 async fn handle_https(
     mut client: TcpStream,
-    config: Arc<Config>,
+    shared: Arc<Shared>,
+    initial: Vec<u8>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut buf: Vec<u8> = Vec::with_capacity(8192);
+    let mut buf: Vec<u8> = initial;
     let mut tmp = [0u8; READ_CHUNK];
 
-    let sni: Option<String> = timeout(TLS_CLIENTHELLO_READ_TIMEOUT, async {
+    let hello: Option<ClientHelloInfo> = timeout(TLS_CLIENTHELLO_READ_TIMEOUT, async {
+        // The prefix handed over by the detect listener may already hold the
+        // whole ClientHello; check before blocking on another read.
+        if let Some(info) = extract_client_hello(&buf) {
+            return Some(info);
+        }
         loop {
             match client.read(&mut tmp).await {
                 Ok(0) => return None,
@@ -159,8 +474,8 @@ async fn handle_https(
                     }
                     buf.extend_from_slice(&tmp[..n]);
 
-                    if let Some(sni) = extract_sni(&buf) {
-                        return Some(sni);
+                    if let Some(info) = extract_client_hello(&buf) {
+                        return Some(info);
                     }
 
                     if buf.len() >= MAX_TLS_INITIAL {
@@ -174,18 +489,37 @@ async fn handle_https(
     .await
     .unwrap_or(None);
 
-    let Some(sni) = sni else {
+    // Routing still keys on SNI; a ClientHello without it cannot be dispatched.
+    let Some(hello) = hello else {
+        return Ok(());
+    };
+    let Some(sni) = hello.sni.clone() else {
         return Ok(());
     };
 
-    let backend_addr = match config.backends.get(&sni) {
-        Some(addr) => addr.clone(),
+    // Operators can opt a host into termination; everything else keeps the
+    // original SNI passthrough behaviour so certless installs are unaffected.
+    let terminate = matches!(
+        shared.config.tls.get(&sni).map(|h| h.mode),
+        Some(TlsMode::Terminate)
+    );
+
+    if terminate {
+        if let Some(acceptor) = shared.tls_acceptor.clone() {
+            let prefixed = PrefixStream::new(buf, client);
+            let tls = acceptor.accept(prefixed).await?;
+            return proxy_http(tls, shared).await;
+        }
+    }
+
+    let backend_addr = match shared.choose_backend(&sni, &hello.alpn) {
+        Some(addr) => addr,
         None => return Ok(()),
     };
 
-    let mut backend = match timeout(BACKEND_CONNECT_TIMEOUT, TcpStream::connect(&backend_addr)).await {
-        Ok(Ok(s)) => s,
-        _ => return Ok(()),
+    let mut backend = match connect_backend(&shared, &backend_addr).await {
+        Ok(s) => s,
+        Err(_) => return Ok(()),
     };
 
     backend.write_all(&buf).await?;
@@ -198,35 +532,386 @@ async fn handle_https(
     Ok(())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TlsMode {
+    Passthrough,
+    Terminate,
+}
+
+impl Default for TlsMode {
+    fn default() -> Self { TlsMode::Passthrough }
+}
+
+#[derive(Debug, Deserialize)]
+struct TlsHost {
+    #[serde(default)]
+    mode: TlsMode,
+    cert: Option<String>,
+    key: Option<String>,
+}
+
+fn default_health_interval_secs() -> u64 { 10 }
+
+/// Accept either a single `"host:port"` string or an array of them for each
+/// backend entry, normalising both to a `Vec` so routing has one shape to work
+/// with.
+fn de_backends<'de, D>(d: D) -> Result<HashMap<String, Vec<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let raw: HashMap<String, OneOrMany> = HashMap::deserialize(d)?;
+    Ok(raw
+        .into_iter()
+        .map(|(k, v)| {
+            let list = match v {
+                OneOrMany::One(s) => vec![s],
+                OneOrMany::Many(m) => m,
+            };
+            (k, list)
+        })
+        .collect())
+}
+
 #[derive(Debug, Deserialize)]
 struct Config {
-    backends: HashMap<String, String>,
+    #[serde(deserialize_with = "de_backends")]
+    backends: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    tls: HashMap<String, TlsHost>,
+    #[serde(default = "default_health_interval_secs")]
+    health_check_interval_secs: u64,
+    /// Optional single port that auto-detects TLS vs plaintext per connection,
+    /// for deployments that cannot reserve both 80 and 443.
+    #[serde(default)]
+    detect_port: Option<u16>,
+    /// Skip certificate verification when re-encrypting to `https://` backends.
+    /// Intended for internal meshes whose backends are not externally rooted.
+    #[serde(default)]
+    backend_tls_insecure: bool,
+}
+
+impl Config {
+    /// The config key whose backend pool serves `(host, alpn)`. A
+    /// protocol-qualified key `host#proto` wins over the bare host, so
+    /// operators can route e.g. `api.example.com#h2` separately from plain
+    /// `api.example.com`. ALPN entries are tried in the client's advertised
+    /// order.
+    fn matched_key(&self, host: &str, alpn: &[String]) -> Option<String> {
+        for proto in alpn {
+            let key = format!("{host}#{proto}");
+            if self.backends.contains_key(&key) {
+                return Some(key);
+            }
+        }
+        if self.backends.contains_key(host) {
+            return Some(host.to_string());
+        }
+        None
+    }
+
+    /// Every distinct backend address referenced by the config. Used to seed
+    /// the health map so probing covers exactly what routing can select.
+    fn backend_addrs(&self) -> Vec<String> {
+        let mut addrs: Vec<String> = self.backends.values().flatten().cloned().collect();
+        addrs.sort();
+        addrs.dedup();
+        addrs
+    }
+}
+
+/// Per-backend liveness, updated out of band by the health-check task and read
+/// on the hot path before we commit to a connection.
+#[derive(Debug)]
+struct BackendHealth {
+    up: AtomicBool,
+    last_check: Mutex<Option<SystemTime>>,
+}
+
+impl BackendHealth {
+    fn new() -> Self {
+        // Start optimistic so traffic flows before the first probe completes.
+        Self { up: AtomicBool::new(true), last_check: Mutex::new(None) }
+    }
+}
+
+/// Shared liveness table keyed by backend address. The set of keys is fixed at
+/// startup from the config, so lookups never mutate the map.
+#[derive(Debug, Default)]
+struct HealthState {
+    backends: HashMap<String, BackendHealth>,
+}
+
+impl HealthState {
+    fn from_config(config: &Config) -> Self {
+        let mut backends = HashMap::new();
+        for addr in config.backend_addrs() {
+            backends.insert(addr, BackendHealth::new());
+        }
+        HealthState { backends }
+    }
+
+    /// Whether a backend may currently receive traffic. Unknown addresses
+    /// (never seeded) are treated as up so routing is never blocked by a gap.
+    fn is_up(&self, addr: &str) -> bool {
+        match self.backends.get(addr) {
+            Some(h) => h.up.load(Ordering::Relaxed),
+            None => true,
+        }
+    }
+
+    fn record(&self, addr: &str, up: bool) {
+        if let Some(h) = self.backends.get(addr) {
+            h.up.store(up, Ordering::Relaxed);
+            if let Ok(mut ts) = h.last_check.lock() {
+                *ts = Some(SystemTime::now());
+            }
+        }
+    }
+}
+
+/// Minimal hand-rolled HTTP liveness probe: open a connection (re-encrypting to
+/// `https://` backends), send a bare `GET /`, and accept any parseable
+/// `HTTP/1.x` status line in the 2xx/3xx/4xx range. Connection refusal or
+/// timeout counts as down.
+async fn probe_backend(shared: &Shared, entry: &str) -> bool {
+    let mut stream = match connect_backend(shared, entry).await {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+
+    let target = parse_backend_uri(entry);
+    let host = &target.host;
+    let req = format!("GET / HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n");
+    if timeout(BACKEND_CONNECT_TIMEOUT, stream.write_all(req.as_bytes())).await.is_err() {
+        return false;
+    }
+
+    let mut buf: Vec<u8> = Vec::with_capacity(256);
+    let mut tmp = [0u8; READ_CHUNK];
+    let read = timeout(BACKEND_CONNECT_TIMEOUT, async {
+        loop {
+            match stream.read(&mut tmp).await {
+                Ok(0) => return,
+                Ok(n) => {
+                    buf.extend_from_slice(&tmp[..n]);
+                    if buf.len() >= HEALTH_PROBE_MAX_BYTES || find_http_header_end(&buf).is_some()
+                        || buf.windows(2).any(|w| w == b"\r\n")
+                    {
+                        return;
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    })
+    .await;
+
+    if read.is_err() {
+        return false;
+    }
+    status_line_is_healthy(&buf)
+}
+
+/// Parse the first line of a response and decide whether it marks the backend
+/// up. Accepts `HTTP/1.x <code>` with a 2xx/3xx/4xx code.
+fn status_line_is_healthy(buf: &[u8]) -> bool {
+    let end = buf.windows(2).position(|w| w == b"\r\n").unwrap_or(buf.len());
+    let line = match std::str::from_utf8(&buf[..end]) {
+        Ok(l) => l,
+        Err(_) => return false,
+    };
+    let mut parts = line.split(' ');
+    let version = match parts.next() {
+        Some(v) => v,
+        None => return false,
+    };
+    if !version.starts_with("HTTP/1.") {
+        return false;
+    }
+    match parts.next().and_then(|c| c.parse::<u16>().ok()) {
+        Some(code) => (200..500).contains(&code),
+        None => false,
+    }
+}
+
+/// Background task: probe every configured backend on a fixed interval and
+/// publish the result into the shared `HealthState`. Probes fan out
+/// concurrently so one slow backend cannot stall liveness updates for the rest.
+async fn health_check_loop(shared: Arc<Shared>, interval: Duration) {
+    loop {
+        let addrs: Vec<String> = shared.health.backends.keys().cloned().collect();
+        let mut handles = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            let app = shared.clone();
+            handles.push(tokio::spawn(async move {
+                let up = probe_backend(&app, &addr).await;
+                (addr, up)
+            }));
+        }
+        for handle in handles {
+            if let Ok((addr, up)) = handle.await {
+                shared.health.record(&addr, up);
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Process-wide shared state handed to every connection task. Holds the parsed
+/// config plus, when any host asks for termination, the prepared rustls
+/// acceptor.
+struct Shared {
+    config: Config,
+    tls_acceptor: Option<TlsAcceptor>,
+    health: Arc<HealthState>,
+    /// Per-host round-robin cursor, seeded from the backend keys at startup.
+    rr: HashMap<String, AtomicUsize>,
+    backend_connector: TlsConnector,
+}
+
+impl Shared {
+    fn new(config: Config, tls_acceptor: Option<TlsAcceptor>, health: Arc<HealthState>) -> Self {
+        let rr = config.backends.keys().map(|k| (k.clone(), AtomicUsize::new(0))).collect();
+        let backend_connector = build_backend_connector(&config);
+        Self { config, tls_acceptor, health, rr, backend_connector }
+    }
+
+    /// Pick a backend for `(host, alpn)`: round-robin across the host's pool,
+    /// skipping any address currently marked down, and return `None` only when
+    /// the host is unknown or every candidate is down.
+    fn choose_backend(&self, host: &str, alpn: &[String]) -> Option<String> {
+        let key = self.config.matched_key(host, alpn)?;
+        let list = self.config.backends.get(&key)?;
+        if list.is_empty() {
+            return None;
+        }
+
+        let start = self
+            .rr
+            .get(&key)
+            .map(|c| c.fetch_add(1, Ordering::Relaxed))
+            .unwrap_or(0);
+
+        for i in 0..list.len() {
+            let addr = &list[(start + i) % list.len()];
+            if self.health.is_up(addr) {
+                return Some(addr.clone());
+            }
+        }
+        None
+    }
+}
+
+/// Load a PEM cert chain + private key and turn it into a rustls `CertifiedKey`.
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey, Box<dyn std::error::Error + Send + Sync>> {
+    let cert_pem = std::fs::read(cert_path)?;
+    let key_pem = std::fs::read(key_path)?;
+
+    let certs = rustls_pemfile::certs(&mut &cert_pem[..]).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {cert_path}").into());
+    }
+    let key = rustls_pemfile::private_key(&mut &key_pem[..])?
+        .ok_or_else(|| format!("no private key found in {key_path}"))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)?;
+
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// Build a SNI-aware `ServerConfig` from every host configured to terminate.
+/// Returns `None` when no host requests termination, leaving the gateway in
+/// pure passthrough mode.
+fn build_tls_acceptor(config: &Config) -> Result<Option<TlsAcceptor>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut keys: HashMap<String, Arc<CertifiedKey>> = HashMap::new();
+
+    for (host, tls) in &config.tls {
+        if tls.mode != TlsMode::Terminate {
+            continue;
+        }
+        let (Some(cert), Some(key)) = (tls.cert.as_deref(), tls.key.as_deref()) else {
+            return Err(format!("host {host} is set to terminate but is missing cert/key").into());
+        };
+        keys.insert(host.clone(), Arc::new(load_certified_key(cert, key)?));
+    }
+
+    if keys.is_empty() {
+        return Ok(None);
+    }
+
+    let server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(CertResolver { keys }));
+
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
     let config_path = std::env::args().nth(1).unwrap_or_else(|| "servers.toml".to_string());
     let srversstr = std::fs::read_to_string(&config_path)?;
     let config: Config = toml::from_str(&srversstr)?;
-    let config = Arc::new(config);
+    let tls_acceptor = build_tls_acceptor(&config)?;
+    let health = Arc::new(HealthState::from_config(&config));
+    let health_interval = Duration::from_secs(config.health_check_interval_secs);
+    let shared = Arc::new(Shared::new(config, tls_acceptor, health));
+
+    tokio::spawn(health_check_loop(shared.clone(), health_interval));
     let printcfg = srversstr.replace("\n", " ");
     let ts = chrono::DateTime::<Utc>::from(SystemTime::now()).to_rfc3339_opts(SecondsFormat::Millis, true);
 
+    // Single-port detect mode replaces the fixed 80/443 listeners entirely, so
+    // an operator who cannot reserve both ports can still start the gateway.
+    if let Some(detect_port) = shared.config.detect_port {
+        let detect = TcpListener::bind(format!("0.0.0.0:{detect_port}")).await?;
+        println!("{ts} <-> kiagateway >>> service starting: single-port auto-detect (TLS/plaintext) on port {detect_port}");
+        println!("{ts} <-> kiagateway >>> service config loaded: {}", printcfg);
+
+        loop {
+            match detect.accept().await {
+                Ok((socket, addr)) => {
+                    let app = shared.clone();
+                    let txid = Uuid::new_v4().to_string();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_detect(socket, app).await {
+                            let ts = chrono::DateTime::<Utc>::from(SystemTime::now()).to_rfc3339_opts(SecondsFormat::Millis, true);
+                            println!("{ts} - {txid} - kiagateway >>> DETECT ERROR {}: {}", addr, e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    let txid = Uuid::new_v4().to_string();
+                    let ts = chrono::DateTime::<Utc>::from(SystemTime::now()).to_rfc3339_opts(SecondsFormat::Millis, true);
+                    println!("{ts} - {txid} - kiagateway >>> DETECT accept ERROR: {}", e);
+                }
+            }
+        }
+    }
+
     println!("{ts} <-> kiagateway >>> service starting: HTTP (host header inspection) on port 80, HTTPS (passthrough inspection) on port 443");
     println!("{ts} <-> kiagateway >>> service config loaded: {}", printcfg);
 
     let http = TcpListener::bind("0.0.0.0:80").await?;
-    let config_http = config.clone();
+    let shared_http = shared.clone();
 
     tokio::spawn(async move {
         loop {
             match http.accept().await {
                 Ok((socket, addr)) => {
-                    let cfg = config_http.clone();
+                    let app = shared_http.clone();
                     let txid = Uuid::new_v4().to_string();
                     tokio::spawn(async move {
-                        if let Err(e) = handle_http(socket, cfg).await {
+                        if let Err(e) = handle_http(socket, app).await {
                             let ts = chrono::DateTime::<Utc>::from(SystemTime::now()).to_rfc3339_opts(SecondsFormat::Millis, true);
                             println!("{ts} - {txid} - kiagateway >>> HTTP ERROR {}: {}", addr, e);
                         }
@@ -242,15 +927,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     });
 
     let https = TcpListener::bind("0.0.0.0:443").await?;
-    let config_https = config.clone();
+    let shared_https = shared.clone();
 
     loop {
         match https.accept().await {
             Ok((socket, addr)) => {
-                let cfg = config_https.clone();
+                let app = shared_https.clone();
                 let txid = Uuid::new_v4().to_string();
                 tokio::spawn(async move {
-                    if let Err(e) = handle_https(socket, cfg).await {
+                    if let Err(e) = handle_https(socket, app, Vec::new()).await {
                         let ts = chrono::DateTime::<Utc>::from(SystemTime::now()).to_rfc3339_opts(SecondsFormat::Millis, true);
                         println!("{ts} - {txid} - kiagateway >>> HTTPS ERROR {}: {}", addr, e);
                     }
@@ -353,7 +1038,20 @@ fn is_valid_sni(s: &str) -> bool {
     h.bytes().all(|c| matches!(c, b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'-'))
 }
 
-fn extract_sni_from_clienthello_handshake(handshake_msg: &[u8]) -> Option<String> {
+/// What we learn from peeking a ClientHello: the SNI host (when present and
+/// valid) and the ALPN protocols the client advertised, in wire order. Both
+/// feed routing — ALPN lets an operator split, say, `h2` and `http/1.1` for the
+/// same host onto different backends.
+#[derive(Debug, Default, Clone)]
+struct ClientHelloInfo {
+    sni: Option<String>,
+    alpn: Vec<String>,
+}
+
+/// Parse the server_name (ext 0) and ALPN (ext 16) entries from a single,
+/// fully-buffered ClientHello handshake message. Returns `None` only when the
+/// message itself is malformed; a missing SNI or ALPN is just an empty field.
+fn extract_client_hello_from_handshake(handshake_msg: &[u8]) -> Option<ClientHelloInfo> {
     let mut c = Cur::new(handshake_msg);
     let msg_type = c.u8()?;
     if msg_type != 0x01 { return None; }
@@ -374,6 +1072,8 @@ fn extract_sni_from_clienthello_handshake(handshake_msg: &[u8]) -> Option<String
     let exts = ch.take(exts_len)?;
     let mut ex = Cur::new(exts);
 
+    let mut info = ClientHelloInfo::default();
+
     while ex.rem() >= 4 {
         let ext_type = ex.u16()? as u16;
         let ext_len = ex.u16()? as usize;
@@ -397,20 +1097,46 @@ fn extract_sni_from_clienthello_handshake(handshake_msg: &[u8]) -> Option<String
                     let name_str = std::str::from_utf8(name_bytes).ok()?;
                     let name_lc = name_str.to_ascii_lowercase();
                     if is_valid_sni(&name_lc) {
-                        return Some(name_lc);
+                        info.sni = Some(name_lc);
                     } else {
                         return None;
                     }
+                    break;
                 }
             }
-            return None;
+        } else if ext_type == 16 {
+            // ALPN is advisory for routing: a malformed list keeps only the
+            // protocols parsed before the fault (possibly none) and never
+            // discards an otherwise valid SNI.
+            info.alpn = parse_alpn(ext_data);
         }
     }
 
-    None
+    Some(info)
+}
+
+/// Parse the ALPN `ProtocolNameList` (a 2-byte length then length-prefixed
+/// protocol IDs) on a best-effort basis, stopping at the first bounds or UTF-8
+/// error and returning whatever was collected so far.
+fn parse_alpn(ext_data: &[u8]) -> Vec<String> {
+    let mut protocols = Vec::new();
+    let mut al = Cur::new(ext_data);
+    let Some(list_len) = al.u16().map(|v| v as usize) else { return protocols; };
+    if al.rem() < list_len { return protocols; }
+    let Some(list) = al.take(list_len) else { return protocols; };
+    let mut pi = Cur::new(list);
+
+    while pi.rem() >= 1 {
+        let Some(proto_len) = pi.u8().map(|v| v as usize) else { break; };
+        if proto_len == 0 || pi.rem() < proto_len { break; }
+        let Some(proto_bytes) = pi.take(proto_len) else { break; };
+        let Ok(proto) = std::str::from_utf8(proto_bytes) else { break; };
+        protocols.push(proto.to_string());
+    }
+    protocols
 }
 
-fn extract_sni(data: &[u8]) -> Option<String> {
+fn extract_client_hello(data: &[u8]) -> Option<ClientHelloInfo> {
     let mut i = 0usize;
     let mut records_seen = 0usize;
     let mut hs_accum: Vec<u8> = Vec::with_capacity(4096);
@@ -448,7 +1174,7 @@ fn extract_sni(data: &[u8]) -> Option<String> {
             continue;
         }
 
-        return extract_sni_from_clienthello_handshake(&hs_accum[..total]);
+        return extract_client_hello_from_handshake(&hs_accum[..total]);
     }
 
     None